@@ -6,6 +6,34 @@ use serde::{Deserialize, Serialize};
 
 use crate::utils::id::TenantId;
 
+/// Identifies a relation block, an SLRU page, or a piece of metadata within the keyspace
+/// that pageserver stores.  This is a local, trimmed-down stand-in for `pageserver_api::key::Key`:
+/// only the fields needed to decide which shard a key belongs to are represented here.
+#[derive(Eq, PartialEq, Clone, Copy, Debug)]
+pub struct Key {
+    /// Discriminates the kind of key: `0x00` is a regular relation block, anything else
+    /// is SLRU/metadata/control-file territory and is never split across shards.
+    pub field1: u8,
+    pub field2: u32,
+    pub field3: u32,
+    pub field4: u32,
+    pub field5: u8,
+    pub field6: u32,
+}
+
+impl Key {
+    /// True for keys that address a block within a normal relation (table/index), i.e. the
+    /// ones that are safe to stripe across shards. Everything else (SLRU segments, the
+    /// checkpoint, relation metadata, ...) must be visible to every shard.
+    pub fn is_rel_block_key(&self) -> bool {
+        self.field1 == 0x00
+    }
+
+    fn hash_combine(a: u32, b: u32) -> u32 {
+        (a ^ b).wrapping_mul(0x9e3779b9)
+    }
+}
+
 #[derive(Ord, PartialOrd, Eq, PartialEq, Clone, Copy, Serialize, Deserialize, Debug, Hash)]
 pub struct ShardNumber(pub u8);
 
@@ -21,6 +49,55 @@ pub struct ShardIndex {
 #[derive(Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Debug)]
 pub struct ShardStripeSize(pub u32);
 
+/// Describes how a tenant's keyspace is divided into shards, and provides the routing logic
+/// (`get_shard_number` / `is_key_local`) that `pageserver_api::shard` would otherwise have to
+/// reimplement: given this tenant's shard layout, which shard owns a given key?
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct ShardIdentity {
+    pub number: ShardNumber,
+    pub count: ShardCount,
+    pub stripe_size: ShardStripeSize,
+}
+
+impl ShardIdentity {
+    /// Determine which shard a key belongs to under this tenant's layout.
+    ///
+    /// Unsharded tenants (`count < 2`) and keys that must be visible on every shard (SLRU
+    /// segments, metadata, control keys) always resolve to shard 0.  Relation block keys are
+    /// hashed over their relation-identifying fields and striped across shards in runs of
+    /// `stripe_size` consecutive blocks, so that sequential scans stay mostly on one shard.
+    pub fn get_shard_number(&self, key: &Key) -> ShardNumber {
+        if self.count.0 < 2 {
+            return ShardNumber(0);
+        }
+
+        if !key.is_rel_block_key() {
+            // SLRU, metadata and control keys must be replicated to every shard.
+            return ShardNumber(0);
+        }
+
+        let mut hash = murmurhash32::murmurhash2(&key.field2.to_be_bytes());
+        hash = Key::hash_combine(hash, murmurhash32::murmurhash2(&key.field3.to_be_bytes()));
+        hash = Key::hash_combine(hash, murmurhash32::murmurhash2(&key.field4.to_be_bytes()));
+        hash = Key::hash_combine(hash, murmurhash32::murmurhash2(&[key.field5]));
+
+        // The stripe index is folded in with a plain wrapping add, not `hash_combine`: this
+        // mirrors `pageserver_api::shard`'s real algorithm exactly, so the control plane and
+        // pageserver agree on which shard owns a given key.
+        let stripe = key.field6 / self.stripe_size.0;
+        hash = hash.wrapping_add(stripe);
+
+        ShardNumber((hash % self.count.0 as u32) as u8)
+    }
+
+    /// Whether `key` belongs to this identity's own shard. Always true for unsharded tenants,
+    /// and for non-stripeable keys (SLRU/metadata/control), since those are replicated to
+    /// every shard rather than owned by shard 0 specifically.
+    pub fn is_key_local(&self, key: &Key) -> bool {
+        self.count.0 < 2 || !key.is_rel_block_key() || self.get_shard_number(key) == self.number
+    }
+}
+
 pub struct ShardSlug<'a>(&'a TenantShardId);
 
 #[derive(Eq, PartialEq, PartialOrd, Ord, Clone, Copy, Hash)]
@@ -111,6 +188,60 @@ impl std::str::FromStr for TenantShardId {
     }
 }
 
+impl TenantShardId {
+    /// Zero-copy, allocation-free counterpart to [`FromStr`]: consumes exactly the 32- or
+    /// 37-byte textual prefix of a `TenantShardId` from a byte iterator, validating hex digits
+    /// as it goes, and leaves the iterator positioned immediately after the parsed id. This
+    /// lets callers tokenize a larger buffer in a single pass -- e.g. a remote path like
+    /// `tenants/<id>-<slug>/timelines/...` -- without first copying out a substring, mirroring
+    /// the incremental, iterator-driven parsers used by crates like `bitcoin` and `preserves`.
+    ///
+    /// Returns [`hex::FromHexError::InvalidStringLength`] if fewer bytes than required remain.
+    /// On error, `data` is left exactly where it started -- nothing is consumed unless parsing
+    /// succeeds -- so callers can fall back to parsing something else from the same position.
+    pub fn parse_prefix(data: &mut core::slice::Iter<'_, u8>) -> Result<Self, hex::FromHexError> {
+        // Do all consuming on a throwaway clone, and only commit it back to `data` once we
+        // know the whole id parsed successfully.
+        let mut cursor = data.clone();
+
+        let mut tenant_id_hex = [0u8; 32];
+        for slot in tenant_id_hex.iter_mut() {
+            *slot = *cursor.next().ok_or(hex::FromHexError::InvalidStringLength)?;
+        }
+        let tenant_id = TenantId::from_hex(tenant_id_hex)?;
+
+        // Peek at the next byte on a further clone: a shard suffix starts with '-', and only
+        // advance `cursor` past it once we know whether it's there.
+        let mut lookahead = cursor.clone();
+        let result = if lookahead.next() == Some(&b'-') {
+            let mut shard_hex = [0u8; 4];
+            for slot in shard_hex.iter_mut() {
+                *slot = *lookahead
+                    .next()
+                    .ok_or(hex::FromHexError::InvalidStringLength)?;
+            }
+            let mut shard_parts = [0u8; 2];
+            hex::decode_to_slice(shard_hex, &mut shard_parts)?;
+            cursor = lookahead;
+            Self {
+                tenant_id,
+                shard_number: ShardNumber(shard_parts[0]),
+                shard_count: ShardCount(shard_parts[1]),
+            }
+        } else {
+            // Legacy case: no shard specified.
+            Self {
+                tenant_id,
+                shard_number: ShardNumber(0),
+                shard_count: ShardCount(0),
+            }
+        };
+
+        *data = cursor;
+        Ok(result)
+    }
+}
+
 impl From<[u8; 18]> for TenantShardId {
     fn from(b: [u8; 18]) -> Self {
         let tenant_id_bytes: [u8; 16] = b[0..16].try_into().unwrap();
@@ -164,6 +295,173 @@ impl From<[u8; 2]> for ShardIndex {
     }
 }
 
+impl ShardIndex {
+    /// True if `self` is the shard that `other` was split from, i.e. `other`'s keyspace is
+    /// a subset of `self`'s.  Used when a tenant is split from `ShardCount(N)` to some multiple
+    /// `ShardCount(M)`, to find which of the new child shards owns data that used to live on
+    /// `self`.
+    pub fn is_ancestor_of(&self, other: &ShardIndex) -> bool {
+        // ShardCount(0) is the legacy "unsharded" index (see `TenantShardId`'s `FromStr`/
+        // `Display` impls): it has no meaningful descendants, and dividing by it would panic.
+        if self.shard_count.0 == 0 {
+            return false;
+        }
+
+        other.shard_count.0.is_multiple_of(self.shard_count.0)
+            && other.shard_number.0 % self.shard_count.0 == self.shard_number.0
+    }
+
+    /// Enumerate the shards that `self` splits into when the tenant's shard count grows from
+    /// `self.shard_count` to `new_count`.  `new_count` must be an integer multiple of
+    /// `self.shard_count`; every resulting `ShardIndex` satisfies `is_ancestor_of` w.r.t `self`.
+    ///
+    /// A legacy (`ShardCount(0)`) index has no descendants and returns an empty `Vec`.
+    pub fn split(&self, new_count: ShardCount) -> Vec<ShardIndex> {
+        if self.shard_count.0 == 0 {
+            return Vec::new();
+        }
+
+        (0..new_count.0)
+            .filter(|n| n % self.shard_count.0 == self.shard_number.0)
+            .map(|n| ShardIndex {
+                shard_number: ShardNumber(n),
+                shard_count: new_count,
+            })
+            .collect()
+    }
+}
+
+impl TenantShardId {
+    /// See [`ShardIndex::is_ancestor_of`]: same relationship, but comparing the `TenantShardId`s
+    /// of two generations of the same tenant.
+    pub fn is_ancestor_of(&self, other: &TenantShardId) -> bool {
+        self.tenant_id == other.tenant_id
+            && ShardIndex::from(*self).is_ancestor_of(&ShardIndex::from(*other))
+    }
+
+    /// See [`ShardIndex::split`]: enumerate this tenant's child shards after a split to
+    /// `new_count`.
+    pub fn split(&self, new_count: ShardCount) -> Vec<TenantShardId> {
+        ShardIndex::from(*self)
+            .split(new_count)
+            .into_iter()
+            .map(|index| TenantShardId {
+                tenant_id: self.tenant_id,
+                shard_number: index.shard_number,
+                shard_count: index.shard_count,
+            })
+            .collect()
+    }
+}
+
+impl From<TenantShardId> for ShardIndex {
+    fn from(id: TenantShardId) -> Self {
+        Self {
+            shard_number: id.shard_number,
+            shard_count: id.shard_count,
+        }
+    }
+}
+
+/// Current version of [`TenantShardId::encode_versioned`]'s wire format. Bump this whenever
+/// the payload layout changes, and teach [`TenantShardId::decode_versioned`] the new layout
+/// without breaking its ability to read older (or, within reason, newer) versions.
+const TENANT_SHARD_ID_WIRE_VERSION: u8 = 1;
+
+/// Number of payload bytes used by wire format version 1: 16 bytes of [`TenantId`] followed
+/// by one byte each of shard number and shard count.
+const TENANT_SHARD_ID_V1_PAYLOAD_LEN: usize = 18;
+
+/// Errors from [`TenantShardId::decode_versioned`].
+#[derive(Debug, Eq, PartialEq)]
+pub enum TenantShardIdDecodeError {
+    /// Fewer bytes were supplied than the `[version, len]` header requires.
+    TooShort,
+    /// The length byte claims more payload than was actually supplied.
+    LengthMismatch,
+    /// The payload is shorter than the format version requires to decode the fields this
+    /// crate understands.
+    TruncatedPayload { version: u8, required: usize, found: usize },
+}
+
+impl std::fmt::Display for TenantShardIdDecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooShort => write!(f, "buffer is shorter than the version+length header"),
+            Self::LengthMismatch => write!(f, "declared payload length exceeds buffer length"),
+            Self::TruncatedPayload {
+                version,
+                required,
+                found,
+            } => write!(
+                f,
+                "version {version} payload requires at least {required} bytes, found {found}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TenantShardIdDecodeError {}
+
+impl TenantShardId {
+    /// Self-describing binary encoding: `[version: u8][len: u8][payload: len bytes]`.
+    ///
+    /// Unlike the bare `[u8; 18]` this type used to hand to the non-human-readable serde path,
+    /// this format can grow: a future version can append fields (e.g. a layout discriminant)
+    /// after the 18 bytes used today, and a reader that only understands version 1 will ignore
+    /// the extra trailing bytes rather than failing to decode. Every field in today's payload
+    /// is a single byte (or a raw byte array), so there's no endianness to pin down yet; if a
+    /// future version adds a genuine multi-byte integer field, encode it at a fixed width with
+    /// an explicit byte order, the way `bincode` does, so the layout stays deterministic across
+    /// platforms.
+    pub fn encode_versioned(&self) -> Vec<u8> {
+        let mut payload = [0u8; TENANT_SHARD_ID_V1_PAYLOAD_LEN];
+        payload[0..16].clone_from_slice(&self.tenant_id.as_arr());
+        payload[16] = self.shard_number.0;
+        payload[17] = self.shard_count.0;
+
+        let mut out = Vec::with_capacity(2 + payload.len());
+        out.push(TENANT_SHARD_ID_WIRE_VERSION);
+        out.push(payload.len() as u8);
+        out.extend_from_slice(&payload);
+        out
+    }
+
+    /// Inverse of [`Self::encode_versioned`]. Decodes version 1 payloads (today's 18-byte
+    /// layout), and for forward compatibility also accepts any newer version whose payload is
+    /// at least as long as version 1's: trailing bytes appended by a newer writer are ignored.
+    pub fn decode_versioned(data: &[u8]) -> Result<Self, TenantShardIdDecodeError> {
+        if data.len() < 2 {
+            return Err(TenantShardIdDecodeError::TooShort);
+        }
+        let version = data[0];
+        let len = data[1] as usize;
+        let rest = &data[2..];
+        if rest.len() < len {
+            return Err(TenantShardIdDecodeError::LengthMismatch);
+        }
+        let payload = &rest[..len];
+
+        if payload.len() < TENANT_SHARD_ID_V1_PAYLOAD_LEN {
+            return Err(TenantShardIdDecodeError::TruncatedPayload {
+                version,
+                required: TENANT_SHARD_ID_V1_PAYLOAD_LEN,
+                found: payload.len(),
+            });
+        }
+
+        // Version 1's layout is also our baseline for decoding any newer, unknown version:
+        // readers only need to understand the fields that existed when they were built, and
+        // bytes appended after them by a newer writer are simply ignored.
+        let tenant_id_bytes: [u8; 16] = payload[0..16].try_into().unwrap();
+        Ok(Self {
+            tenant_id: TenantId::from(tenant_id_bytes),
+            shard_number: ShardNumber(payload[16]),
+            shard_count: ShardCount(payload[17]),
+        })
+    }
+}
+
 impl Serialize for TenantShardId {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -172,14 +470,7 @@ impl Serialize for TenantShardId {
         if serializer.is_human_readable() {
             serializer.collect_str(self)
         } else {
-            // Note: while human encoding of [`TenantShardId`] is backward and forward
-            // compatible, this binary encoding is not.
-            let mut packed: [u8; 18] = [0; 18];
-            packed[0..16].clone_from_slice(&self.tenant_id.as_arr());
-            packed[16] = self.shard_number.0;
-            packed[17] = self.shard_count.0;
-
-            packed.serialize(serializer)
+            serializer.serialize_bytes(&self.encode_versioned())
         }
     }
 }
@@ -200,17 +491,26 @@ impl<'de> Deserialize<'de> for TenantShardId {
                 if self.is_human_readable_deserializer {
                     formatter.write_str("value in form of hex string")
                 } else {
-                    formatter.write_str("value in form of integer array([u8; 18])")
+                    formatter.write_str("value in form of versioned byte buffer")
                 }
             }
 
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                TenantShardId::decode_versioned(v).map_err(E::custom)
+            }
+
             fn visit_seq<A>(self, seq: A) -> Result<Self::Value, A::Error>
             where
                 A: serde::de::SeqAccess<'de>,
             {
+                // Some formats represent a byte slice as a sequence of individual u8 elements
+                // rather than calling visit_bytes; fall back to collecting them.
                 let s = serde::de::value::SeqAccessDeserializer::new(seq);
-                let id: [u8; 18] = Deserialize::deserialize(s)?;
-                Ok(TenantShardId::from(id))
+                let bytes: Vec<u8> = Deserialize::deserialize(s)?;
+                TenantShardId::decode_versioned(&bytes).map_err(serde::de::Error::custom)
             }
 
             fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -226,12 +526,9 @@ impl<'de> Deserialize<'de> for TenantShardId {
                 is_human_readable_deserializer: true,
             })
         } else {
-            deserializer.deserialize_tuple(
-                18,
-                IdVisitor {
-                    is_human_readable_deserializer: false,
-                },
-            )
+            deserializer.deserialize_bytes(IdVisitor {
+                is_human_readable_deserializer: false,
+            })
         }
     }
 }
@@ -306,3 +603,443 @@ impl<'de> Deserialize<'de> for ShardIndex {
         }
     }
 }
+
+/// Optional CBOR tagged-value encoding for [`TenantShardId`] and [`ShardIndex`], for interop
+/// with non-Rust CBOR consumers that need to tell a shard id apart from an arbitrary byte
+/// string. Gated behind the `cbor-tags` feature since it pulls in `serde_cbor` and most callers
+/// (our own IPC and remote metadata) are happy with the plain byte-string encoding above.
+///
+/// Wherever the `serde_cbor` dependency is declared for the `cbor-tags` feature, it must be
+/// declared with `features = ["tags"]`. Without that feature, `serde_cbor` silently ignores
+/// `Tagged`'s tag and serializes only the inner byte string (CBOR major type 2/4, not the
+/// tag's major type 6) -- `set_tag`/`from_tagged` would still compile and the `Tagged` value
+/// would still report `tag == Some(..)` on the Rust side, but nothing tagged would actually
+/// reach the wire, defeating the entire point of this module for non-Rust consumers.
+#[cfg(feature = "cbor-tags")]
+pub mod cbor_tags {
+    use serde_cbor::tags::Tagged;
+
+    use super::{ShardIndex, TenantShardId, TenantShardIdDecodeError};
+
+    /// Tag number identifying a [`TenantShardId`]'s versioned byte payload.
+    ///
+    /// This is *not* an IANA-registered CBOR tag -- RFC 8949 section 9.2 only reserves 0-23
+    /// for standards-track registration and 24-255 for specification-required registration;
+    /// everything above that, including this value, is first-come-first-served and unregistered.
+    /// Treat it as project-private: it only has meaning between producers and consumers that
+    /// both link this crate, and a future real registration at this number would collide with
+    /// it. Register it with IANA before relying on it for external interop.
+    pub const TENANT_SHARD_ID_TAG: u64 = 55800;
+
+    /// Tag number identifying a [`ShardIndex`]'s 2-byte payload. See
+    /// [`TENANT_SHARD_ID_TAG`] for the same unregistered/project-private caveat.
+    pub const SHARD_INDEX_TAG: u64 = 55801;
+
+    impl TenantShardId {
+        /// Wrap this id's versioned binary payload (see [`Self::encode_versioned`]) in a CBOR
+        /// semantic tag, so a tag-aware decoder can recognize it as a shard id rather than an
+        /// opaque byte string. Serializers that don't support tagging fall back to emitting the
+        /// inner byte string untagged.
+        pub fn set_tag(&self) -> Tagged<Vec<u8>> {
+            Tagged::new(Some(TENANT_SHARD_ID_TAG), self.encode_versioned())
+        }
+
+        /// Inverse of [`Self::set_tag`]. Accepts both the tagged form and a plain, untagged
+        /// byte string (`tag` is `None`), so callers don't need to know in advance whether the
+        /// producer emitted a tag.
+        pub fn from_tagged(tagged: Tagged<Vec<u8>>) -> Result<Self, TenantShardIdDecodeError> {
+            Self::decode_versioned(&tagged.value)
+        }
+    }
+
+    impl ShardIndex {
+        /// Wrap this index's packed `[shard_number, shard_count]` payload in a CBOR semantic
+        /// tag, mirroring [`TenantShardId::set_tag`].
+        pub fn set_tag(&self) -> Tagged<Vec<u8>> {
+            Tagged::new(
+                Some(SHARD_INDEX_TAG),
+                vec![self.shard_number.0, self.shard_count.0],
+            )
+        }
+
+        /// Inverse of [`Self::set_tag`]; also accepts an untagged byte string.
+        pub fn from_tagged(tagged: Tagged<Vec<u8>>) -> Result<Self, hex::FromHexError> {
+            let bytes: [u8; 2] = tagged
+                .value
+                .try_into()
+                .map_err(|_| hex::FromHexError::InvalidStringLength)?;
+            Ok(ShardIndex::from(bytes))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rel_block_key(relnode: u32, blknum: u32) -> Key {
+        Key {
+            field1: 0x00,
+            field2: 1663,
+            field3: 16384,
+            field4: relnode,
+            field5: 0,
+            field6: blknum,
+        }
+    }
+
+    #[test]
+    fn unsharded_always_maps_to_shard_zero() {
+        let identity = ShardIdentity {
+            number: ShardNumber(0),
+            count: ShardCount(1),
+            stripe_size: ShardStripeSize(100),
+        };
+
+        for blknum in [0, 1, 99, 100, 12345] {
+            let key = rel_block_key(12345, blknum);
+            assert_eq!(identity.get_shard_number(&key), ShardNumber(0));
+            assert!(identity.is_key_local(&key));
+        }
+    }
+
+    #[test]
+    fn non_stripeable_keys_are_replicated_to_every_shard() {
+        let identity = ShardIdentity {
+            number: ShardNumber(1),
+            count: ShardCount(4),
+            stripe_size: ShardStripeSize(100),
+        };
+
+        let slru_key = Key {
+            field1: 0x01,
+            field2: 0,
+            field3: 0,
+            field4: 0,
+            field5: 0,
+            field6: 42,
+        };
+
+        assert_eq!(identity.get_shard_number(&slru_key), ShardNumber(0));
+        assert!(identity.is_key_local(&slru_key));
+    }
+
+    #[test]
+    fn stripes_are_distributed_roughly_evenly() {
+        const STRIPE_SIZE: u32 = 32;
+        const SHARD_COUNT: u8 = 8;
+        const NUM_STRIPES: u32 = 10_000;
+
+        let mut counts = [0u32; SHARD_COUNT as usize];
+        for stripe in 0..NUM_STRIPES {
+            let identity = ShardIdentity {
+                number: ShardNumber(0),
+                count: ShardCount(SHARD_COUNT),
+                stripe_size: ShardStripeSize(STRIPE_SIZE),
+            };
+            let key = rel_block_key(54321, stripe * STRIPE_SIZE);
+            let shard = identity.get_shard_number(&key);
+            counts[shard.0 as usize] += 1;
+        }
+
+        let expected = NUM_STRIPES / SHARD_COUNT as u32;
+        for count in counts {
+            let deviation = (count as i64 - expected as i64).unsigned_abs();
+            assert!(
+                deviation < expected as u64 / 4,
+                "shard counts were not evenly distributed: {counts:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn same_stripe_maps_to_same_shard() {
+        let identity = ShardIdentity {
+            number: ShardNumber(0),
+            count: ShardCount(4),
+            stripe_size: ShardStripeSize(10),
+        };
+
+        let a = rel_block_key(999, 23);
+        let b = rel_block_key(999, 29);
+        assert_eq!(identity.get_shard_number(&a), identity.get_shard_number(&b));
+    }
+
+    #[test]
+    fn get_shard_number_matches_pinned_expectation() {
+        // Pins the exact output of the routing algorithm for a fixed key/stripe_size/count, so
+        // a change to the hashing that moves this key to a different shard shows up as a test
+        // failure rather than silently diverging from `pageserver_api::shard`'s implementation.
+        // A single pinned key only catches changes that alter its shard: for this key, folding
+        // the raw stripe index through `hash_combine` instead of `wrapping_add` also yields 1.
+        let identity = ShardIdentity {
+            number: ShardNumber(0),
+            count: ShardCount(6),
+            stripe_size: ShardStripeSize(4),
+        };
+        let key = rel_block_key(16421, 777);
+
+        assert_eq!(identity.get_shard_number(&key), ShardNumber(1));
+    }
+
+    #[test]
+    fn shard_index_split_enumerates_descendants() {
+        let parent = ShardIndex {
+            shard_number: ShardNumber(1),
+            shard_count: ShardCount(2),
+        };
+
+        let children = parent.split(ShardCount(8));
+        assert_eq!(
+            children,
+            vec![
+                ShardIndex {
+                    shard_number: ShardNumber(1),
+                    shard_count: ShardCount(8)
+                },
+                ShardIndex {
+                    shard_number: ShardNumber(3),
+                    shard_count: ShardCount(8)
+                },
+                ShardIndex {
+                    shard_number: ShardNumber(5),
+                    shard_count: ShardCount(8)
+                },
+                ShardIndex {
+                    shard_number: ShardNumber(7),
+                    shard_count: ShardCount(8)
+                },
+            ]
+        );
+
+        for child in &children {
+            assert!(parent.is_ancestor_of(child));
+        }
+    }
+
+    #[test]
+    fn shard_index_is_not_ancestor_of_unrelated_shard() {
+        let parent = ShardIndex {
+            shard_number: ShardNumber(0),
+            shard_count: ShardCount(2),
+        };
+        let unrelated = ShardIndex {
+            shard_number: ShardNumber(3),
+            shard_count: ShardCount(8),
+        };
+
+        assert!(!parent.is_ancestor_of(&unrelated));
+    }
+
+    #[test]
+    fn tenant_shard_id_split_preserves_tenant_id() {
+        let tenant_id = TenantId::from([0x42; 16]);
+        let parent = TenantShardId {
+            tenant_id,
+            shard_number: ShardNumber(0),
+            shard_count: ShardCount(1),
+        };
+
+        let children = parent.split(ShardCount(4));
+        assert_eq!(children.len(), 4);
+        for child in &children {
+            assert_eq!(child.tenant_id, tenant_id);
+            assert!(parent.is_ancestor_of(child));
+        }
+    }
+
+    #[test]
+    fn legacy_shard_count_zero_has_no_descendants() {
+        let legacy = ShardIndex {
+            shard_number: ShardNumber(0),
+            shard_count: ShardCount(0),
+        };
+        let other = ShardIndex {
+            shard_number: ShardNumber(1),
+            shard_count: ShardCount(4),
+        };
+
+        // Must not panic on a divide/mod by zero, and must report no relationship/descendants.
+        assert!(!legacy.is_ancestor_of(&other));
+        assert_eq!(legacy.split(ShardCount(4)), Vec::new());
+
+        let legacy_tenant = TenantShardId {
+            tenant_id: TenantId::from([0x66; 16]),
+            shard_number: ShardNumber(0),
+            shard_count: ShardCount(0),
+        };
+        assert!(legacy_tenant.split(ShardCount(4)).is_empty());
+    }
+
+    #[test]
+    fn versioned_encoding_round_trips() {
+        let id = TenantShardId {
+            tenant_id: TenantId::from([0x11; 16]),
+            shard_number: ShardNumber(3),
+            shard_count: ShardCount(8),
+        };
+
+        let encoded = id.encode_versioned();
+        assert_eq!(encoded[0], 1, "version byte");
+        assert_eq!(encoded[1], 18, "length byte");
+
+        let decoded = TenantShardId::decode_versioned(&encoded).unwrap();
+        assert_eq!(decoded, id);
+    }
+
+    #[test]
+    fn v2_buffer_with_trailing_bytes_decodes_under_v1_reader() {
+        let id = TenantShardId {
+            tenant_id: TenantId::from([0x22; 16]),
+            shard_number: ShardNumber(1),
+            shard_count: ShardCount(2),
+        };
+
+        // Simulate a hypothetical future writer: version 2, a 20-byte payload that starts
+        // with today's 18 bytes and appends 2 bytes of some new field this reader doesn't
+        // know about yet.
+        let mut v2_buf = vec![2u8, 20u8];
+        v2_buf.extend_from_slice(&id.tenant_id.as_arr());
+        v2_buf.push(id.shard_number.0);
+        v2_buf.push(id.shard_count.0);
+        v2_buf.extend_from_slice(&[0xaa, 0xbb]);
+
+        let decoded = TenantShardId::decode_versioned(&v2_buf).unwrap();
+        assert_eq!(decoded, id);
+    }
+
+    #[test]
+    fn decode_versioned_rejects_truncated_buffers() {
+        assert_eq!(
+            TenantShardId::decode_versioned(&[1]),
+            Err(TenantShardIdDecodeError::TooShort)
+        );
+        assert_eq!(
+            TenantShardId::decode_versioned(&[1, 18, 0, 0, 0]),
+            Err(TenantShardIdDecodeError::LengthMismatch)
+        );
+    }
+
+    #[cfg(feature = "cbor-tags")]
+    #[test]
+    fn cbor_tag_round_trips_tagged_and_untagged() {
+        use super::cbor_tags::TENANT_SHARD_ID_TAG;
+
+        let id = TenantShardId {
+            tenant_id: TenantId::from([0x33; 16]),
+            shard_number: ShardNumber(2),
+            shard_count: ShardCount(4),
+        };
+
+        let tagged = id.set_tag();
+        assert_eq!(tagged.tag, Some(TENANT_SHARD_ID_TAG));
+        assert_eq!(TenantShardId::from_tagged(tagged).unwrap(), id);
+
+        // An untagged producer is accepted too.
+        let untagged = serde_cbor::tags::Tagged::new(None, id.encode_versioned());
+        assert_eq!(TenantShardId::from_tagged(untagged).unwrap(), id);
+    }
+
+    #[cfg(feature = "cbor-tags")]
+    #[test]
+    fn cbor_tag_is_a_real_cbor_tag_on_the_wire() {
+        use super::cbor_tags::TENANT_SHARD_ID_TAG;
+
+        let id = TenantShardId {
+            tenant_id: TenantId::from([0x88; 16]),
+            shard_number: ShardNumber(5),
+            shard_count: ShardCount(16),
+        };
+
+        let bytes = serde_cbor::to_vec(&id.set_tag()).unwrap();
+
+        // CBOR major type 6 (tag) has its top 3 bits set to 0b110; confirm the byte stream
+        // actually carries a tag header, not just the plain byte-string payload.
+        let major_type = bytes[0] >> 5;
+        assert_eq!(
+            major_type, 6,
+            "expected a CBOR tag on the wire, got major type {major_type}"
+        );
+
+        let decoded: serde_cbor::tags::Tagged<Vec<u8>> = serde_cbor::from_slice(&bytes).unwrap();
+        assert_eq!(decoded.tag, Some(TENANT_SHARD_ID_TAG));
+        assert_eq!(TenantShardId::from_tagged(decoded).unwrap(), id);
+    }
+
+    #[test]
+    fn parse_prefix_tokenizes_sharded_id_from_a_path() {
+        let id = TenantShardId {
+            tenant_id: TenantId::from([0x44; 16]),
+            shard_number: ShardNumber(1),
+            shard_count: ShardCount(8),
+        };
+        let path = format!("tenants/{id}/timelines/foo");
+        let bytes = path.as_bytes();
+
+        let mut iter = bytes.iter();
+        // Skip the "tenants/" prefix the way a caller tokenizing a real path would.
+        for _ in 0.."tenants/".len() {
+            iter.next().unwrap();
+        }
+
+        let parsed = TenantShardId::parse_prefix(&mut iter).unwrap();
+        assert_eq!(parsed, id);
+
+        let remainder: Vec<u8> = iter.copied().collect();
+        assert_eq!(remainder, b"/timelines/foo");
+    }
+
+    #[test]
+    fn parse_prefix_tokenizes_legacy_id_from_a_path() {
+        let id = TenantShardId {
+            tenant_id: TenantId::from([0x55; 16]),
+            shard_number: ShardNumber(0),
+            shard_count: ShardCount(0),
+        };
+        let path = format!("{id}/timelines/foo");
+        let mut iter = path.as_bytes().iter();
+
+        let parsed = TenantShardId::parse_prefix(&mut iter).unwrap();
+        assert_eq!(parsed, id);
+
+        let remainder: Vec<u8> = iter.copied().collect();
+        assert_eq!(remainder, b"/timelines/foo");
+    }
+
+    #[test]
+    fn parse_prefix_rejects_short_buffers() {
+        let mut iter = b"deadbeef"[..].iter();
+        assert_eq!(
+            TenantShardId::parse_prefix(&mut iter),
+            Err(hex::FromHexError::InvalidStringLength)
+        );
+    }
+
+    #[test]
+    fn parse_prefix_leaves_iterator_untouched_on_error() {
+        let buf = b"deadbeef";
+
+        let mut short = buf[..].iter();
+        let before: Vec<u8> = short.clone().copied().collect();
+        assert!(TenantShardId::parse_prefix(&mut short).is_err());
+        let after: Vec<u8> = short.copied().collect();
+        assert_eq!(before, after, "no bytes should be consumed on a short buffer");
+
+        // Also check the truncated-shard-suffix case: 32 valid hex bytes followed by a '-' and
+        // fewer than 4 more hex bytes.
+        let id = TenantShardId {
+            tenant_id: TenantId::from([0x77; 16]),
+            shard_number: ShardNumber(1),
+            shard_count: ShardCount(2),
+        };
+        let truncated = format!("{}-1", id.tenant_id);
+        let mut iter = truncated.as_bytes().iter();
+        let before: Vec<u8> = iter.clone().copied().collect();
+        assert!(TenantShardId::parse_prefix(&mut iter).is_err());
+        let after: Vec<u8> = iter.copied().collect();
+        assert_eq!(
+            before, after,
+            "no bytes should be consumed when the shard suffix is truncated"
+        );
+    }
+}